@@ -0,0 +1,4 @@
+//! Companion implementations for working through [OSTEP](https://pages.cs.wisc.edu/~remzi/OSTEP/).
+#![cfg_attr(test, feature(test))]
+
+pub mod threads;
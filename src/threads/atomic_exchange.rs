@@ -4,23 +4,92 @@
 //!
 //! This Wiki is good to understand atomic memory ordering: <https://gcc.gnu.org/wiki/Atomic/GCCMM/AtomicSync>
 
+use super::futex;
 use std::cell::UnsafeCell;
 use std::ops::{Deref, DerefMut};
-use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 
-const MUTEX_AVAILABLE: u8 = 0;
-const MUTEX_LOCKED: u8 = 1;
-const MUTEX_POISONED: u8 = 2;
+const MUTEX_AVAILABLE: u32 = 0;
+const MUTEX_LOCKED: u32 = 1;
+/// Locked, and at least one thread is (or was, moments ago) parked waiting for it — set by
+/// [`Mutex::lock_adaptive`] so that unlocking knows whether a futex wake is needed.
+const MUTEX_LOCKED_WITH_WAITERS: u32 = 2;
 
-type LockResult<'a, T> = Result<MutexGuard<'a, T>, &'static str>;
+/// How many times [`Mutex::lock_adaptive`] spins before parking the thread.
+const ADAPTIVE_SPIN_ITERATIONS: u32 = 100;
+
+/// A type alias for the result of a lock method which can be poisoned.
+///
+/// Mirrors `std::sync::LockResult`: the `Ok` and `Err(PoisonError)` variants both hold a valid,
+/// already-acquired guard, so the lock is always held when this type is returned.
+type LockResult<'a, T> = Result<MutexGuard<'a, T>, PoisonError<MutexGuard<'a, T>>>;
+
+/// An error returned by a locking method when the mutex was poisoned by a thread that panicked
+/// while holding the lock.
+///
+/// Unlike a bare error message, this carries the guard that was acquired while recovering from
+/// the poison, so a caller who knows the data wasn't actually left in a broken state can pull it
+/// back out with [`PoisonError::into_inner`].
+pub struct PoisonError<G> {
+    guard: G,
+}
+
+impl<G> std::fmt::Debug for PoisonError<G> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        "PoisonError { .. }".fmt(f)
+    }
+}
+
+impl<G> PoisonError<G> {
+    pub(crate) fn new(guard: G) -> Self {
+        PoisonError { guard }
+    }
+
+    /// Consumes this error, returning the guard that was nonetheless acquired.
+    pub fn into_inner(self) -> G {
+        self.guard
+    }
+
+    /// Alias of [`PoisonError::into_inner`] for readers used to `std`'s `MutexGuard`-specific naming.
+    pub fn into_guard(self) -> G {
+        self.guard
+    }
+}
+
+/// A type alias for the result of [`Mutex::try_lock`].
+type TryLockResult<G> = Result<G, TryLockError<G>>;
+
+/// An error returned by [`Mutex::try_lock`].
+///
+/// Unlike [`PoisonError`], this distinguishes a lock that is merely contended (retryable) from
+/// one that is poisoned (a broken invariant, recoverable via [`PoisonError::into_inner`]).
+pub enum TryLockError<G> {
+    /// The lock is currently held by another thread; retrying later may succeed.
+    WouldBlock,
+    /// The lock is poisoned. The wrapped [`PoisonError`] still carries the guard.
+    Poisoned(PoisonError<G>),
+}
+
+impl<G> std::fmt::Debug for TryLockError<G> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TryLockError::WouldBlock => "WouldBlock".fmt(f),
+            TryLockError::Poisoned(_) => "Poisoned(..)".fmt(f),
+        }
+    }
+}
 
 /// A spin-lock Mutex implementation using CAS.
 pub struct Mutex<T> {
     inner: UnsafeCell<T>,
     // 0: available
     // 1: locked
-    // 2: poisoned
-    flag: AtomicU8,
+    // 2: locked, with waiters parked (see `lock_adaptive`)
+    flag: AtomicU32,
+    /// Set once, by a panicking guard's `Drop`, and never cleared: mirrors `std::sync::Mutex`,
+    /// which keeps a mutex poisoned until a caller explicitly invokes `clear_poison`. This crate
+    /// doesn't expose that escape hatch, so poisoning here is permanent.
+    poisoned: AtomicBool,
 }
 
 /// RAII
@@ -32,17 +101,18 @@ impl<T> Mutex<T> {
     pub fn new(inner: T) -> Self {
         Mutex {
             inner: inner.into(),
-            flag: AtomicU8::new(0),
+            flag: AtomicU32::new(MUTEX_AVAILABLE),
+            poisoned: AtomicBool::new(false),
         }
     }
 
     /// "Test and set" lock.
-    pub fn lock(&self) -> LockResult<T> {
+    pub fn lock(&self) -> LockResult<'_, T> {
         self._lock(false)
     }
 
     /// Test And Test And set lock.
-    pub fn lock_ttas(&self) -> LockResult<T> {
+    pub fn lock_ttas(&self) -> LockResult<'_, T> {
         self._lock(true)
     }
 
@@ -54,17 +124,18 @@ impl<T> Mutex<T> {
     //  ::test_and_test_and_set_performance ... bench:   5,527,019.90 ns/iter (+/- 2,446,606.89)
     //
     // # Errors
-    // Will return an error if the lock is poisoned.
+    // Will return `Err(PoisonError)` if the lock is poisoned. The error still carries the
+    // guard, since the lock is acquired regardless.
     //
     // # Notes
     // When yielding instead of `continue`ing, TAS is faster than TTAS.
     // Check later the performance of looping first for some time then yielding (hopefully avoid a
     // syscall).
-    fn _lock(&self, test_and_test: bool) -> LockResult<T> {
+    fn _lock(&self, test_and_test: bool) -> LockResult<'_, T> {
         // Perform an additional test step before the atomic operation.
         // <https://en.wikipedia.org/wiki/Test_and_test-and-set>
         if test_and_test {
-            unsafe { while *self.flag.as_ptr() == 1 {} };
+            unsafe { while *self.flag.as_ptr() == MUTEX_LOCKED {} };
         }
 
         loop {
@@ -85,28 +156,116 @@ impl<T> Mutex<T> {
                 // 1. A big number of syscalls
                 // 2. Does not address starvation
                 //MUTEX_LOCKED => std::thread::yield_now(),
-                Err(MUTEX_LOCKED) => continue,
+                // (see `lock_adaptive` for a lock that actually fixes both)
                 Ok(MUTEX_AVAILABLE) => break,
-                Err(MUTEX_POISONED) => {
-                    return Err("The lock is poinsoned");
-                }
-                _ => unreachable!(),
+                // Also covers `MUTEX_LOCKED_WITH_WAITERS`, which this path never sets itself but
+                // may observe if `lock_adaptive` is used concurrently on the same mutex.
+                Err(_) => continue,
+                Ok(_) => unreachable!(),
+            }
+        }
+        self.acquired_guard()
+    }
+
+    /// Acquire the lock by spinning briefly, then parking the thread on a futex if it's still
+    /// contended, instead of spinning or yielding forever.
+    ///
+    /// This is the fix for the two problems called out in `_lock`'s comments: a bounded spin
+    /// avoids a syscall for every acquisition (most critical sections are short), and parking
+    /// rather than spinning means a long-waiting thread is woken directly instead of being
+    /// starved by other spinners repeatedly winning the CAS race.
+    ///
+    /// # Benchmarks
+    ///
+    /// ::adaptive_performance ... compared against `test_and_set_performance` /
+    /// `test_and_test_and_set_performance` under the same contention.
+    ///
+    /// # Errors
+    /// Will return `Err(PoisonError)` if the lock is poisoned. The error still carries the
+    /// guard, since the lock is acquired regardless.
+    pub fn lock_adaptive(&self) -> LockResult<'_, T> {
+        for _ in 0..ADAPTIVE_SPIN_ITERATIONS {
+            if self
+                .flag
+                .compare_exchange_weak(
+                    MUTEX_AVAILABLE,
+                    MUTEX_LOCKED,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                return self.acquired_guard();
+            }
+            std::hint::spin_loop();
+        }
+
+        // Still contended after the spin budget: register as a waiter and let the OS park us.
+        loop {
+            match self.flag.swap(MUTEX_LOCKED_WITH_WAITERS, Ordering::Acquire) {
+                MUTEX_AVAILABLE => return self.acquired_guard(),
+                _ => futex::wait(&self.flag, MUTEX_LOCKED_WITH_WAITERS),
             }
         }
-        Ok(MutexGuard { mutex: self })
     }
 
-    /// Attempt to acquire a lock.
+    /// Attempt to acquire a lock without blocking.
     ///
     /// # Errors
-    /// Will return an error if the lock is being held by another thread.
-    /// Will return an error if the lock is poisoned.
-    pub fn try_lock(&self) -> LockResult<T> {
-        match self.flag.swap(MUTEX_LOCKED, Ordering::Relaxed) {
-            MUTEX_LOCKED => Err("Lock is not available"),
-            MUTEX_AVAILABLE => Ok(MutexGuard { mutex: self }),
-            MUTEX_POISONED => Err("The lock is poinsoned"),
-            _ => unreachable!(),
+    /// Will return `Err(TryLockError::WouldBlock)` if the lock is being held by another thread.
+    /// Will return `Err(TryLockError::Poisoned)` if the lock is poisoned; like [`Mutex::lock`],
+    /// the error still carries the guard, since the lock is acquired regardless.
+    pub fn try_lock(&self) -> TryLockResult<MutexGuard<'_, T>> {
+        // Unlike a plain `swap`, `compare_exchange` only takes the lock when it observes
+        // `MUTEX_AVAILABLE`, so attempting to lock an already-locked mutex no longer rewrites
+        // the flag out from under its owner.
+        match self.flag.compare_exchange(
+            MUTEX_AVAILABLE,
+            MUTEX_LOCKED,
+            Ordering::Acquire,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => self.acquired_guard().map_err(TryLockError::Poisoned),
+            // `MUTEX_LOCKED` or `MUTEX_LOCKED_WITH_WAITERS`: either way, someone else holds it.
+            Err(_) => Err(TryLockError::WouldBlock),
+        }
+    }
+
+    /// Returns `true` if the mutex is currently poisoned.
+    ///
+    /// Once poisoned, a mutex stays poisoned forever: unlike `std::sync::Mutex`, this crate
+    /// doesn't expose a `clear_poison` escape hatch, so every subsequent `lock` keeps returning
+    /// `Err(PoisonError)` (and an uncontended `try_lock` `Err(TryLockError::Poisoned)`) even though
+    /// the guard it carries is still usable.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Relaxed)
+    }
+
+    /// Wraps a just-acquired lock in a guard, reporting poison from an earlier panic if any.
+    ///
+    /// Shared by every acquisition path (`_lock`, `lock_adaptive`, `try_lock`) so they agree on
+    /// what "poisoned" means.
+    fn acquired_guard(&self) -> LockResult<'_, T> {
+        if self.poisoned.load(Ordering::Relaxed) {
+            Err(PoisonError::new(MutexGuard { mutex: self }))
+        } else {
+            Ok(MutexGuard { mutex: self })
+        }
+    }
+
+    /// Releases the lock, waking a parked `lock_adaptive` waiter if there was one.
+    ///
+    /// Shared by `MutexGuard::drop` and [`MutexGuard::release_for_wait`] so the two don't drift
+    /// out of sync. Poisoning is sticky: once `poisoned` is set it is never cleared here, since
+    /// only a panicking guard's `Drop` sets `true` and a normal unlock always passes `false`.
+    fn unlock(&self, poisoned: bool) {
+        if poisoned {
+            self.poisoned.store(true, Ordering::Relaxed);
+        }
+        let previous = self.flag.swap(MUTEX_AVAILABLE, Ordering::Release);
+        // Only `lock_adaptive` ever sets `MUTEX_LOCKED_WITH_WAITERS`, so only it needs a wake.
+        if previous == MUTEX_LOCKED_WITH_WAITERS {
+            futex::wake_one(&self.flag);
         }
     }
 }
@@ -124,14 +283,24 @@ impl<T> DerefMut for MutexGuard<'_, T> {
     }
 }
 
+impl<'a, T> MutexGuard<'a, T> {
+    /// Releases the lock without poisoning it, skipping the rest of `Drop`'s cleanup, and hands
+    /// back the `Mutex` this guard borrowed from.
+    ///
+    /// This exists for [`crate::threads::condvar::Condvar::wait`], which needs to unlock the
+    /// mutex, block until notified, and then reacquire it — `self` can't simply be dropped for
+    /// that, since `Drop` doesn't give the caller anything back to reacquire with.
+    pub(crate) fn release_for_wait(self) -> &'a Mutex<T> {
+        let mutex = self.mutex;
+        std::mem::forget(self);
+        mutex.unlock(false);
+        mutex
+    }
+}
+
 impl<T> Drop for MutexGuard<'_, T> {
     fn drop(&mut self) {
-        let flag = if std::thread::panicking() {
-            MUTEX_POISONED
-        } else {
-            MUTEX_AVAILABLE
-        };
-        self.mutex.flag.store(flag, Ordering::Release);
+        self.mutex.unlock(std::thread::panicking());
     }
 }
 
@@ -140,7 +309,7 @@ unsafe impl<T: Sync> Sync for Mutex<T> {}
 
 #[cfg(test)]
 mod tests {
-    use super::{Mutex, MUTEX_AVAILABLE};
+    use super::{Mutex, TryLockError, MUTEX_AVAILABLE};
     use std::sync::atomic::Ordering;
     use std::sync::Arc;
     extern crate test;
@@ -174,6 +343,94 @@ mod tests {
         assert!(mutex.lock().is_err());
     }
 
+    #[test]
+    fn it_recovers_the_data_from_a_poisoned_lock() {
+        let mutex = Arc::new(Mutex::new(5));
+        {
+            let mutex = Arc::clone(&mutex);
+            let _ = std::thread::spawn(move || {
+                let _num = mutex.lock().unwrap();
+                panic!("Intentionally poison the lock");
+            })
+            .join();
+        }
+
+        assert!(mutex.is_poisoned());
+        let Err(poison_error) = mutex.lock() else {
+            panic!("expected the lock to be poisoned");
+        };
+        let guard = poison_error.into_inner();
+        assert_eq!(5, *guard);
+    }
+
+    #[test]
+    fn poison_stays_sticky_after_recovery() {
+        let mutex = Arc::new(Mutex::new(5));
+        {
+            let mutex = Arc::clone(&mutex);
+            let _ = std::thread::spawn(move || {
+                let _num = mutex.lock().unwrap();
+                panic!("Intentionally poison the lock");
+            })
+            .join();
+        }
+
+        // Recovering from the poison once must not clear it for everyone after.
+        let Err(poison_error) = mutex.lock() else {
+            panic!("expected the lock to be poisoned");
+        };
+        drop(poison_error.into_inner());
+        assert!(mutex.is_poisoned());
+        assert!(mutex.lock().is_err());
+        assert!(mutex.is_poisoned());
+    }
+
+    #[test]
+    fn try_lock_distinguishes_would_block_from_poisoned() {
+        let mutex = Mutex::new(5);
+        let _guard = mutex.lock().unwrap();
+
+        let result = mutex.try_lock();
+        match result {
+            Err(TryLockError::WouldBlock) => {}
+            _ => panic!("expected WouldBlock while the lock is already held"),
+        }
+
+        let poisoned = Arc::new(Mutex::new(5));
+        {
+            let poisoned = Arc::clone(&poisoned);
+            let _ = std::thread::spawn(move || {
+                let _num = poisoned.lock().unwrap();
+                panic!("Intentionally poison the lock");
+            })
+            .join();
+        }
+        match poisoned.try_lock() {
+            Err(TryLockError::Poisoned(poison_error)) => assert_eq!(5, *poison_error.into_inner()),
+            _ => panic!("expected Poisoned from an unlocked but poisoned mutex"),
+        }
+    }
+
+    #[test]
+    fn it_parks_and_wakes_with_the_adaptive_lock() {
+        let mutex = Arc::new(Mutex::new(0));
+        let guard = mutex.lock_adaptive().unwrap();
+
+        let waiter = {
+            let mutex = Arc::clone(&mutex);
+            std::thread::spawn(move || {
+                let mut guard = mutex.lock_adaptive().unwrap();
+                *guard += 1;
+            })
+        };
+
+        // Give the waiter long enough to exhaust its spin budget and actually park.
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        drop(guard);
+        waiter.join().unwrap();
+        assert_eq!(1, *mutex.lock_adaptive().unwrap());
+    }
+
     #[bench]
     fn test_and_set_performance(b: &mut test::Bencher) {
         b.iter(|| {
@@ -231,4 +488,33 @@ mod tests {
             assert_eq!(50000, *counter.lock().unwrap());
         });
     }
+
+    #[bench]
+    fn adaptive_performance(b: &mut test::Bencher) {
+        b.iter(|| {
+            let counter = Arc::new(Mutex::new(0));
+
+            const COUNT: usize = 10;
+            let mut handles: [Option<std::thread::JoinHandle<()>>; COUNT] =
+                unsafe { std::mem::zeroed() };
+
+            // spawn `COUNT` threads all incrementing the same counter.
+            (0..COUNT).for_each(|i| {
+                let counter = Arc::clone(&counter);
+                handles[i] = Some(std::thread::spawn(move || {
+                    let mut counter = counter.lock_adaptive().unwrap();
+                    for _ in 0..test::black_box(5000) {
+                        *counter += test::black_box(1);
+                    }
+                }));
+            });
+
+            // join the threads.
+            (0..COUNT).for_each(|i| {
+                handles[i].take().unwrap().join().unwrap();
+            });
+
+            assert_eq!(50000, *counter.lock().unwrap());
+        });
+    }
 }
@@ -0,0 +1,126 @@
+//! A condition variable built on top of [`crate::threads::atomic_exchange::Mutex`].
+//!
+//! Lets a thread wait for some condition on shared state to become true instead of busy-spinning
+//! on it (e.g. the counter examples in this crate), parking on a futex exactly like
+//! [`Mutex::lock_adaptive`][super::atomic_exchange::Mutex::lock_adaptive] does.
+
+use super::atomic_exchange::MutexGuard;
+use super::futex;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// A condition variable associated with a [`Mutex`][super::atomic_exchange::Mutex].
+pub struct Condvar {
+    /// Bumped by `notify_one`/`notify_all`; waiters park on whatever value they observed before
+    /// releasing the mutex and recheck it once woken.
+    generation: AtomicU32,
+}
+
+impl Condvar {
+    pub fn new() -> Self {
+        Condvar {
+            generation: AtomicU32::new(0),
+        }
+    }
+
+    /// Atomically releases `guard`'s mutex and blocks the current thread until notified, then
+    /// reacquires the mutex before returning.
+    ///
+    /// The reacquired guard is handed back even if the mutex was poisoned while this thread was
+    /// waiting, mirroring [`Mutex::lock`][super::atomic_exchange::Mutex::lock]'s own recovery story.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let mutex = guard.release_for_wait();
+
+        while self.generation.load(Ordering::Acquire) == generation {
+            futex::wait(&self.generation, generation);
+        }
+
+        match mutex.lock_adaptive() {
+            Ok(guard) => guard,
+            Err(poison_error) => poison_error.into_inner(),
+        }
+    }
+
+    /// Wakes one waiting thread, if any.
+    pub fn notify_one(&self) {
+        self.generation.fetch_add(1, Ordering::Release);
+        futex::wake_one(&self.generation);
+    }
+
+    /// Wakes all waiting threads.
+    pub fn notify_all(&self) {
+        self.generation.fetch_add(1, Ordering::Release);
+        futex::wake_all(&self.generation);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Condvar;
+    use crate::threads::atomic_exchange::Mutex;
+    use std::sync::Arc;
+
+    #[test]
+    fn it_wakes_a_waiter_on_notify_one() {
+        let pair = Arc::new((Mutex::new(false), Condvar::new()));
+
+        let waiter = {
+            let pair = Arc::clone(&pair);
+            std::thread::spawn(move || {
+                let (mutex, condvar) = &*pair;
+                let mut ready = mutex.lock().unwrap();
+                while !*ready {
+                    ready = condvar.wait(ready);
+                }
+            })
+        };
+
+        // Give the waiter a chance to actually start waiting before notifying it.
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        {
+            let (mutex, condvar) = &*pair;
+            let mut ready = mutex.lock().unwrap();
+            *ready = true;
+            condvar.notify_one();
+        }
+
+        waiter.join().unwrap();
+    }
+
+    #[test]
+    fn it_wakes_all_waiters_on_notify_all() {
+        let pair = Arc::new((Mutex::new(false), Condvar::new()));
+        const WAITERS: usize = 5;
+
+        let handles: Vec<_> = (0..WAITERS)
+            .map(|_| {
+                let pair = Arc::clone(&pair);
+                std::thread::spawn(move || {
+                    let (mutex, condvar) = &*pair;
+                    let mut ready = mutex.lock().unwrap();
+                    while !*ready {
+                        ready = condvar.wait(ready);
+                    }
+                })
+            })
+            .collect();
+
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        {
+            let (mutex, condvar) = &*pair;
+            let mut ready = mutex.lock().unwrap();
+            *ready = true;
+            condvar.notify_all();
+        }
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+}
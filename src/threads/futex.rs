@@ -0,0 +1,83 @@
+//! A thin wrapper around the Linux futex syscall, used by [`crate::threads::atomic_exchange`]'s
+//! adaptive lock to park a thread instead of spinning once a lock is contended for a while.
+//!
+//! There is no portable equivalent without pulling in extra dependencies, so non-Linux targets
+//! fall back to a plain spin loop.
+
+use std::sync::atomic::AtomicU32;
+
+/// Block the current thread while `futex`'s value is still `expected`.
+///
+/// May return spuriously (a signal, a stale wakeup, ...); callers must re-check the value
+/// themselves, exactly as with the underlying `FUTEX_WAIT` syscall.
+pub fn wait(futex: &AtomicU32, expected: u32) {
+    imp::wait(futex, expected);
+}
+
+/// Wake up to one thread parked in [`wait`] on this futex.
+pub fn wake_one(futex: &AtomicU32) {
+    imp::wake_one(futex);
+}
+
+/// Wake every thread parked in [`wait`] on this futex.
+pub fn wake_all(futex: &AtomicU32) {
+    imp::wake_all(futex);
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::sync::atomic::AtomicU32;
+
+    // `libc` doesn't expose these for plain linux-gnu targets, so define them ourselves from
+    // `linux/futex.h` (this is also what the standard library's own futex module does).
+    const FUTEX_WAIT: i32 = 0;
+    const FUTEX_WAKE: i32 = 1;
+    const FUTEX_PRIVATE_FLAG: i32 = 128;
+    const FUTEX_WAIT_PRIVATE: i32 = FUTEX_WAIT | FUTEX_PRIVATE_FLAG;
+    const FUTEX_WAKE_PRIVATE: i32 = FUTEX_WAKE | FUTEX_PRIVATE_FLAG;
+
+    pub fn wait(futex: &AtomicU32, expected: u32) {
+        unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                futex.as_ptr(),
+                FUTEX_WAIT_PRIVATE,
+                expected,
+                std::ptr::null::<libc::timespec>(),
+            );
+        }
+        // The syscall can return for reasons unrelated to the value changing (EAGAIN, EINTR,
+        // spurious wakeups); the caller re-checks the value in a loop, so there's nothing else
+        // to do with the result here.
+    }
+
+    pub fn wake_one(futex: &AtomicU32) {
+        unsafe {
+            libc::syscall(libc::SYS_futex, futex.as_ptr(), FUTEX_WAKE_PRIVATE, 1i32);
+        }
+    }
+
+    pub fn wake_all(futex: &AtomicU32) {
+        unsafe {
+            libc::syscall(libc::SYS_futex, futex.as_ptr(), FUTEX_WAKE_PRIVATE, i32::MAX);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// No futex on this platform: spin until the value changes.
+    pub fn wait(futex: &AtomicU32, expected: u32) {
+        while futex.load(Ordering::Relaxed) == expected {
+            std::hint::spin_loop();
+        }
+    }
+
+    /// There's no one parked to wake directly; waiters here just poll the value instead.
+    pub fn wake_one(_futex: &AtomicU32) {}
+
+    /// There's no one parked to wake directly; waiters here just poll the value instead.
+    pub fn wake_all(_futex: &AtomicU32) {}
+}
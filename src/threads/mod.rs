@@ -0,0 +1,10 @@
+pub mod atomic_exchange;
+// A runnable illustration of the classic lock-ordering deadlock, not a reusable module — its
+// `main` is never invoked by the library, hence the blanket `allow`.
+#[allow(dead_code)]
+mod bug_lock_order;
+pub mod condvar;
+mod futex;
+pub mod reentrant_mutex;
+pub mod rwlock;
+pub mod ticket_lock;
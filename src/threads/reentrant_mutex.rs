@@ -0,0 +1,161 @@
+//! A reentrant (recursive) spin-lock mutex.
+//!
+//! Unlike [`crate::threads::atomic_exchange::Mutex`], the same thread may acquire this lock
+//! multiple times without deadlocking on itself, which is handy for the deadlock examples in
+//! this crate where a function that already holds the lock calls into another function that
+//! also wants it.
+
+use std::cell::UnsafeCell;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// `0` means the mutex is currently unowned; thread ids handed out below start at `1`.
+const UNOWNED: u64 = 0;
+
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    /// A small, dense id for the current thread, assigned on first use.
+    ///
+    /// `std::thread::ThreadId` isn't guaranteed to be representable as a `u64` on stable, so
+    /// each thread is handed one from this counter instead.
+    static THREAD_ID: u64 = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+}
+
+fn current_thread_id() -> u64 {
+    THREAD_ID.with(|id| *id)
+}
+
+/// A mutex that the owning thread may lock more than once.
+pub struct ReentrantMutex<T> {
+    inner: UnsafeCell<T>,
+    /// `0` when unowned, otherwise the id of the thread currently holding the lock.
+    owner: AtomicU64,
+    /// Recursion depth. Only ever touched by the owning thread.
+    count: UnsafeCell<u64>,
+}
+
+/// RAII guard for a [`ReentrantMutex`].
+///
+/// Only derefs to `&T`, never `&mut T`: recursive acquisitions alias the same data, so handing
+/// out a unique reference would be unsound.
+pub struct ReentrantMutexGuard<'a, T> {
+    mutex: &'a ReentrantMutex<T>,
+    /// `drop`'s SAFETY relies on only the owning thread ever touching `count`, which only holds
+    /// if the guard never crosses a thread boundary. `*const ()` isn't `Send`, so this forces the
+    /// same `!Send` guard the recursion invariant needs, matching `std::sync::ReentrantLockGuard`.
+    _not_send: std::marker::PhantomData<*const ()>,
+}
+
+impl<T> ReentrantMutex<T> {
+    pub fn new(inner: T) -> Self {
+        ReentrantMutex {
+            inner: inner.into(),
+            owner: AtomicU64::new(UNOWNED),
+            count: UnsafeCell::new(0),
+        }
+    }
+
+    /// Acquire the lock, blocking (by spinning) if another thread currently owns it.
+    ///
+    /// If the calling thread already owns the lock, this simply bumps the recursion count
+    /// instead of spinning on itself.
+    pub fn lock(&self) -> ReentrantMutexGuard<'_, T> {
+        let my_id = current_thread_id();
+
+        if self.owner.load(Ordering::Relaxed) == my_id {
+            // SAFETY: `count` is only ever touched by the owning thread, and we are it.
+            unsafe { *self.count.get() += 1 };
+            return ReentrantMutexGuard {
+                mutex: self,
+                _not_send: std::marker::PhantomData,
+            };
+        }
+
+        while self
+            .owner
+            .compare_exchange_weak(UNOWNED, my_id, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            std::hint::spin_loop();
+        }
+        // SAFETY: we just became the owner, so we're the only thread allowed to touch `count`.
+        unsafe { *self.count.get() = 1 };
+        ReentrantMutexGuard {
+            mutex: self,
+            _not_send: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<T> Deref for ReentrantMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.mutex.inner.get().as_ref().expect("Inner is not null") }
+    }
+}
+
+impl<T> Drop for ReentrantMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // SAFETY: only the owning thread ever runs this, since only the owning thread can hold
+        // a `ReentrantMutexGuard`.
+        unsafe {
+            *self.mutex.count.get() -= 1;
+            if *self.mutex.count.get() == 0 {
+                self.mutex.owner.store(UNOWNED, Ordering::Release);
+            }
+        }
+    }
+}
+
+unsafe impl<T: Send> Send for ReentrantMutex<T> {}
+unsafe impl<T: Send> Sync for ReentrantMutex<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::ReentrantMutex;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    #[test]
+    fn it_allows_the_owning_thread_to_reacquire_the_lock() {
+        let mutex = ReentrantMutex::new(5);
+
+        let outer = mutex.lock();
+        {
+            // Reacquiring from the same thread must not deadlock.
+            let inner = mutex.lock();
+            assert_eq!(5, *inner);
+        }
+        assert_eq!(1, unsafe { *mutex.count.get() });
+        assert_eq!(5, *outer);
+    }
+
+    #[test]
+    fn it_blocks_other_threads_until_fully_released() {
+        let mutex = Arc::new(ReentrantMutex::new(0));
+        let _outer = mutex.lock();
+        let _inner = mutex.lock();
+
+        let other = {
+            let mutex = Arc::clone(&mutex);
+            std::thread::spawn(move || {
+                let _guard = mutex.lock();
+            })
+        };
+
+        // The other thread can't be holding the lock yet: the owner is still this thread, with
+        // two nested acquisitions outstanding.
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(!other.is_finished());
+        assert_ne!(0, mutex.owner.load(Ordering::Relaxed));
+
+        drop(_inner);
+        // One acquisition is still outstanding, so the other thread must keep waiting.
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(!other.is_finished());
+        drop(_outer);
+        other.join().unwrap();
+    }
+}
@@ -0,0 +1,307 @@
+//! A reader-writer spin lock that avoids starving writers.
+//!
+//! https://pages.cs.wisc.edu/~remzi/OSTEP/threads-locks.pdf
+
+use super::atomic_exchange::PoisonError;
+use std::cell::UnsafeCell;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// The top bit of the state word: set while a writer is either waiting for readers to drain or
+/// actively holding the lock. Once set, new readers must wait behind it instead of being able to
+/// starve the writer indefinitely.
+const WRITER_BIT: usize = 1 << (usize::BITS - 1);
+
+type LockResult<'a, G> = Result<G, PoisonError<G>>;
+
+/// A reader-writer spin-lock: many concurrent readers, or one exclusive writer.
+pub struct RwLock<T> {
+    inner: UnsafeCell<T>,
+    /// Top bit: writer active/pending. Remaining bits: number of active readers.
+    state: AtomicUsize,
+    poisoned: AtomicBool,
+}
+
+/// RAII guard granting shared read access.
+pub struct RwLockReadGuard<'a, T> {
+    lock: &'a RwLock<T>,
+}
+
+/// RAII guard granting exclusive write access.
+pub struct RwLockWriteGuard<'a, T> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T> RwLock<T> {
+    pub fn new(inner: T) -> Self {
+        RwLock {
+            inner: inner.into(),
+            state: AtomicUsize::new(0),
+            poisoned: AtomicBool::new(false),
+        }
+    }
+
+    /// Acquire shared read access, spinning while a writer is active or pending.
+    ///
+    /// # Errors
+    /// Will return `Err(PoisonError)` if a writer panicked while holding the lock. Readers never
+    /// poison the lock themselves.
+    pub fn read(&self) -> LockResult<'_, RwLockReadGuard<'_, T>> {
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+            // A writer that's merely pending still blocks new readers, otherwise a steady stream
+            // of readers could starve it forever.
+            if state & WRITER_BIT != 0 {
+                std::hint::spin_loop();
+                continue;
+            }
+            match self.state.compare_exchange_weak(
+                state,
+                state + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(_) => continue,
+            }
+        }
+
+        if self.poisoned.load(Ordering::Relaxed) {
+            Err(PoisonError::new(RwLockReadGuard { lock: self }))
+        } else {
+            Ok(RwLockReadGuard { lock: self })
+        }
+    }
+
+    /// Acquire exclusive write access, spinning until any pending writer clears and all active
+    /// readers have drained.
+    ///
+    /// # Errors
+    /// Will return `Err(PoisonError)` if a previous writer panicked while holding the lock. The
+    /// error still carries the guard, since the lock is acquired regardless.
+    pub fn write(&self) -> LockResult<'_, RwLockWriteGuard<'_, T>> {
+        // Claim the writer bit first so later readers start blocking immediately, then wait for
+        // readers that were already in the critical section to finish.
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+            if state & WRITER_BIT != 0 {
+                std::hint::spin_loop();
+                continue;
+            }
+            match self.state.compare_exchange_weak(
+                state,
+                state | WRITER_BIT,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(_) => continue,
+            }
+        }
+
+        while self.state.load(Ordering::Acquire) != WRITER_BIT {
+            std::hint::spin_loop();
+        }
+
+        if self.poisoned.load(Ordering::Relaxed) {
+            Err(PoisonError::new(RwLockWriteGuard { lock: self }))
+        } else {
+            Ok(RwLockWriteGuard { lock: self })
+        }
+    }
+
+    /// Returns `true` if a writer has panicked while holding this lock.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.lock.inner.get().as_ref().expect("Inner is not null") }
+    }
+}
+
+impl<T> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl<T> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.lock.inner.get().as_ref().expect("Inner is not null") }
+    }
+}
+
+impl<T> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.lock.inner.get() }
+    }
+}
+
+impl<T> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        // A panicking writer poisons the lock; poisoning is sticky and never cleared by a normal
+        // unlock, matching `atomic_exchange::Mutex`'s recovery story.
+        if std::thread::panicking() {
+            self.lock.poisoned.store(true, Ordering::Relaxed);
+        }
+        self.lock.state.store(0, Ordering::Release);
+    }
+}
+
+unsafe impl<T: Send> Send for RwLock<T> {}
+unsafe impl<T: Send + Sync> Sync for RwLock<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::RwLock;
+    use std::sync::Arc;
+    extern crate test;
+
+    #[test]
+    fn it_allows_multiple_concurrent_readers() {
+        let lock = RwLock::new(5);
+        let a = lock.read().unwrap();
+        let b = lock.read().unwrap();
+        assert_eq!(5, *a);
+        assert_eq!(5, *b);
+    }
+
+    #[test]
+    fn it_gives_exclusive_access_to_a_writer() {
+        let lock = RwLock::new(5);
+        {
+            let mut guard = lock.write().unwrap();
+            *guard = 10;
+        }
+        assert_eq!(10, *lock.read().unwrap());
+    }
+
+    #[test]
+    fn it_blocks_new_readers_while_a_writer_is_pending() {
+        let lock = Arc::new(RwLock::new(0));
+        let _reader = lock.read().unwrap();
+
+        let writer = {
+            let lock = Arc::clone(&lock);
+            std::thread::spawn(move || {
+                let mut guard = lock.write().unwrap();
+                *guard = 1;
+            })
+        };
+
+        // Give the writer time to mark itself pending.
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let late_reader = {
+            let lock = Arc::clone(&lock);
+            std::thread::spawn(move || {
+                let _guard = lock.read().unwrap();
+            })
+        };
+
+        // The late reader can't have acquired the lock yet: the pending writer is still blocked
+        // on `_reader`, and readers must wait behind a pending writer.
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(!writer.is_finished());
+        assert!(!late_reader.is_finished());
+
+        drop(_reader);
+        writer.join().unwrap();
+        late_reader.join().unwrap();
+        assert_eq!(1, *lock.read().unwrap());
+    }
+
+    #[test]
+    fn it_poisons_on_a_panicking_writer_but_not_on_a_panicking_reader() {
+        let lock = Arc::new(RwLock::new(5));
+        {
+            let lock = Arc::clone(&lock);
+            let _ = std::thread::spawn(move || {
+                let _guard = lock.write().unwrap();
+                panic!("Intentionally poison the lock");
+            })
+            .join();
+        }
+        assert!(lock.is_poisoned());
+        assert!(lock.read().is_err());
+
+        let read_lock = Arc::new(RwLock::new(5));
+        {
+            let read_lock = Arc::clone(&read_lock);
+            let _ = std::thread::spawn(move || {
+                let _guard = read_lock.read().unwrap();
+                panic!("A panicking reader must not poison the lock");
+            })
+            .join();
+        }
+        assert!(!read_lock.is_poisoned());
+        assert!(read_lock.read().is_ok());
+    }
+
+    #[test]
+    fn poison_stays_sticky_after_a_normal_write() {
+        let lock = Arc::new(RwLock::new(5));
+        {
+            let lock = Arc::clone(&lock);
+            let _ = std::thread::spawn(move || {
+                let _guard = lock.write().unwrap();
+                panic!("Intentionally poison the lock");
+            })
+            .join();
+        }
+        assert!(lock.is_poisoned());
+
+        // Recovering and completing one ordinary write must not clear the poison.
+        {
+            let Err(poison_error) = lock.write() else {
+                panic!("expected the lock to be poisoned");
+            };
+            let mut guard = poison_error.into_inner();
+            *guard = 10;
+        }
+        assert!(lock.is_poisoned());
+        assert!(lock.read().is_err());
+    }
+
+    const COUNT: usize = 10;
+
+    /// Spawns `COUNT` threads, each performing `reads_per_write` reads for every write, and waits for them.
+    fn run_mixed_workload(reads_per_write: usize) {
+        let lock = Arc::new(RwLock::new(0usize));
+
+        let mut handles: [Option<std::thread::JoinHandle<()>>; COUNT] =
+            unsafe { std::mem::zeroed() };
+        (0..COUNT).for_each(|i| {
+            let lock = Arc::clone(&lock);
+            handles[i] = Some(std::thread::spawn(move || {
+                for _ in 0..test::black_box(200) {
+                    for _ in 0..reads_per_write {
+                        let _ = *lock.read().unwrap();
+                    }
+                    *lock.write().unwrap() += 1;
+                }
+            }));
+        });
+        (0..COUNT).for_each(|i| {
+            handles[i].take().unwrap().join().unwrap();
+        });
+    }
+
+    #[bench]
+    fn read_heavy_contention(b: &mut test::Bencher) {
+        // 9 reads for every write.
+        b.iter(|| run_mixed_workload(9));
+    }
+
+    #[bench]
+    fn write_heavy_contention(b: &mut test::Bencher) {
+        // A single read for every write.
+        b.iter(|| run_mixed_workload(1));
+    }
+}